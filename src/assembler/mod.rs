@@ -0,0 +1,429 @@
+use std::collections::HashMap;
+use crate::instruction::Opcode;
+
+/// Register the assembler reserves to materialize a resolved label address
+/// before jumping through it, since the VM's jump opcodes always read their
+/// target from a register rather than from an immediate.
+const LABEL_SCRATCH_REGISTER: u8 = 31;
+
+#[derive(Debug, PartialEq)]
+pub enum AssemblerError {
+    UnknownMnemonic { line: usize, mnemonic: String },
+    InvalidOperand { line: usize, operand: String },
+    WrongOperandCount { line: usize, mnemonic: String, expected: usize, found: usize },
+    UndefinedLabels(Vec<String>),
+}
+
+#[derive(Debug, Clone)]
+enum Operand {
+    Register(u8),
+    Immediate(u16),
+    Label(String),
+}
+
+struct ParsedLine {
+    label: Option<String>,
+    mnemonic: String,
+    operands: Vec<Operand>,
+    line_number: usize,
+}
+
+/// The shape of the operands a mnemonic expects, used both to size each
+/// instruction during the label-resolving pass and to emit its bytes.
+enum OperandShape {
+    Zero,
+    OneRegister,
+    TwoRegisters,
+    ThreeRegisters,
+    RegisterAndImmediate,
+    JumpTarget,
+}
+
+fn mnemonic_to_opcode(mnemonic: &str) -> Option<Opcode> {
+    match mnemonic {
+        "HLT" => Some(Opcode::HLT),
+        "LOAD" => Some(Opcode::LOAD),
+        "ADD" => Some(Opcode::ADD),
+        "SUB" => Some(Opcode::SUB),
+        "MUL" => Some(Opcode::MUL),
+        "DIV" => Some(Opcode::DIV),
+        "MOV" => Some(Opcode::MOV),
+        "JMP" => Some(Opcode::JMP),
+        "JMPB" => Some(Opcode::JMPB),
+        "JMPF" => Some(Opcode::JMPF),
+        "LB" => Some(Opcode::LB),
+        "LD" => Some(Opcode::LD),
+        "SB" => Some(Opcode::SB),
+        "SD" => Some(Opcode::SD),
+        "ALLOC" => Some(Opcode::ALLOC),
+        "EQ" => Some(Opcode::EQ),
+        "NEQ" => Some(Opcode::NEQ),
+        "GT" => Some(Opcode::GT),
+        "LT" => Some(Opcode::LT),
+        "GTE" => Some(Opcode::GTE),
+        "LTE" => Some(Opcode::LTE),
+        "JEQ" => Some(Opcode::JEQ),
+        "JNE" => Some(Opcode::JNE),
+        "ADDF" => Some(Opcode::ADDF),
+        "SUBF" => Some(Opcode::SUBF),
+        "MULF" => Some(Opcode::MULF),
+        "DIVF" => Some(Opcode::DIVF),
+        "DIVU" => Some(Opcode::DIVU),
+        "ECALL" => Some(Opcode::ECALL),
+        "IRET" => Some(Opcode::IRET),
+        _ => None,
+    }
+}
+
+fn operand_shape(opcode: Opcode) -> OperandShape {
+    match opcode {
+        Opcode::HLT | Opcode::IRET => OperandShape::Zero,
+        Opcode::LOAD => OperandShape::RegisterAndImmediate,
+        Opcode::ADD | Opcode::SUB | Opcode::MUL | Opcode::DIV
+            | Opcode::ADDF | Opcode::SUBF | Opcode::MULF | Opcode::DIVF
+            | Opcode::DIVU => OperandShape::ThreeRegisters,
+        Opcode::MOV | Opcode::EQ | Opcode::NEQ | Opcode::GT | Opcode::LT
+            | Opcode::GTE | Opcode::LTE | Opcode::LB | Opcode::LD
+            | Opcode::SB | Opcode::SD => OperandShape::TwoRegisters,
+        Opcode::ALLOC | Opcode::ECALL => OperandShape::OneRegister,
+        Opcode::JMP | Opcode::JMPB | Opcode::JMPF | Opcode::JEQ | Opcode::JNE => {
+            OperandShape::JumpTarget
+        },
+        Opcode::IGL => OperandShape::Zero,
+    }
+}
+
+/// Number of bytes a mnemonic's instruction takes up once assembled,
+/// including the opcode byte. A jump to a label expands into a LOAD of the
+/// resolved address followed by the jump itself.
+fn instruction_len(opcode: Opcode, operands: &[Operand]) -> usize {
+    match operand_shape(opcode) {
+        OperandShape::Zero => 1,
+        OperandShape::OneRegister => 2,
+        OperandShape::TwoRegisters => 3,
+        OperandShape::ThreeRegisters => 4,
+        OperandShape::RegisterAndImmediate => 4,
+        OperandShape::JumpTarget => match operands.get(0) {
+            Some(Operand::Label(_)) => 6,
+            _ => 2,
+        },
+    }
+}
+
+fn parse_operand(token: &str, line_number: usize) -> Result<Operand, AssemblerError> {
+    let mut chars = token.chars();
+    match chars.next() {
+        Some('$') => {
+            let rest = &token[1..];
+            rest.parse::<u8>()
+                .map(Operand::Register)
+                .map_err(|_| AssemblerError::InvalidOperand {
+                    line: line_number,
+                    operand: token.to_string(),
+                })
+        },
+        Some('#') => {
+            let rest = &token[1..];
+            rest.parse::<u16>()
+                .map(Operand::Immediate)
+                .map_err(|_| AssemblerError::InvalidOperand {
+                    line: line_number,
+                    operand: token.to_string(),
+                })
+        },
+        Some('@') => Ok(Operand::Label(token[1..].to_string())),
+        _ => Err(AssemblerError::InvalidOperand {
+            line: line_number,
+            operand: token.to_string(),
+        }),
+    }
+}
+
+fn parse_lines(source: &str) -> Result<Vec<ParsedLine>, AssemblerError> {
+    let mut lines = vec![];
+
+    for (index, raw_line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let mut tokens = raw_line.split_whitespace().peekable();
+
+        let mut label = None;
+        if let Some(first) = tokens.peek() {
+            if first.ends_with(':') {
+                let name = first.trim_end_matches(':').to_string();
+                label = Some(name);
+                tokens.next();
+            }
+        }
+
+        let mnemonic = match tokens.next() {
+            Some(m) => m.to_uppercase(),
+            None => {
+                if let Some(name) = label {
+                    lines.push(ParsedLine {
+                        label: Some(name),
+                        mnemonic: String::new(),
+                        operands: vec![],
+                        line_number,
+                    });
+                }
+                continue;
+            }
+        };
+
+        let mut operands = vec![];
+        for token in tokens {
+            operands.push(parse_operand(token, line_number)?);
+        }
+
+        lines.push(ParsedLine { label, mnemonic, operands, line_number });
+    }
+
+    Ok(lines)
+}
+
+/// Compiles a line-oriented assembly program (`LOAD $0 #500`, `ADD $0 $1
+/// $2`, `loop: JMP @loop`) into the bytecode the VM runs.
+///
+/// This is a two-pass assembler: the first pass walks every line to record
+/// each label's byte offset, and the second emits opcode and operand bytes,
+/// resolving label references against the table built in the first pass.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssemblerError> {
+    let lines = parse_lines(source)?;
+
+    // Pass 1: record the byte offset of every label declaration.
+    let mut labels: HashMap<String, u32> = HashMap::new();
+    let mut offset: u32 = 0;
+    for line in &lines {
+        if let Some(name) = &line.label {
+            labels.insert(name.clone(), offset);
+        }
+        if line.mnemonic.is_empty() {
+            continue;
+        }
+        let opcode = mnemonic_to_opcode(&line.mnemonic)
+            .ok_or_else(|| AssemblerError::UnknownMnemonic {
+                line: line.line_number,
+                mnemonic: line.mnemonic.clone(),
+            })?;
+        offset += instruction_len(opcode, &line.operands) as u32;
+    }
+
+    // Any label referenced by a jump but never declared is a hard error;
+    // collect all of them so the caller sees the whole list at once.
+    let mut undefined = vec![];
+    for line in &lines {
+        for operand in &line.operands {
+            if let Operand::Label(name) = operand {
+                if !labels.contains_key(name) {
+                    undefined.push(name.clone());
+                }
+            }
+        }
+    }
+    if !undefined.is_empty() {
+        undefined.sort();
+        undefined.dedup();
+        return Err(AssemblerError::UndefinedLabels(undefined));
+    }
+
+    // Pass 2: emit bytes, back-patching label references against `labels`.
+    let mut bytes = vec![];
+    for line in &lines {
+        if line.mnemonic.is_empty() {
+            continue;
+        }
+        let opcode = mnemonic_to_opcode(&line.mnemonic).ok_or_else(|| {
+            AssemblerError::UnknownMnemonic {
+                line: line.line_number,
+                mnemonic: line.mnemonic.clone(),
+            }
+        })?;
+        emit_instruction(&mut bytes, opcode, &line.operands, &labels, line.line_number)?;
+    }
+
+    Ok(bytes)
+}
+
+fn expect_register(operand: Option<&Operand>, line_number: usize) -> Result<u8, AssemblerError> {
+    match operand {
+        Some(Operand::Register(r)) => Ok(*r),
+        Some(other) => Err(AssemblerError::InvalidOperand {
+            line: line_number,
+            operand: format!("{:?}", other),
+        }),
+        None => Err(AssemblerError::InvalidOperand {
+            line: line_number,
+            operand: String::from("<missing>"),
+        }),
+    }
+}
+
+fn push_immediate(bytes: &mut Vec<u8>, value: u16) {
+    bytes.push((value >> 8) as u8);
+    bytes.push((value & 0xff) as u8);
+}
+
+fn emit_instruction(
+    bytes: &mut Vec<u8>,
+    opcode: Opcode,
+    operands: &[Operand],
+    labels: &HashMap<String, u32>,
+    line_number: usize,
+) -> Result<(), AssemblerError> {
+    let shape = operand_shape(opcode);
+    let expected = match shape {
+        OperandShape::Zero => 0,
+        OperandShape::OneRegister => 1,
+        OperandShape::TwoRegisters => 2,
+        OperandShape::ThreeRegisters => 3,
+        OperandShape::RegisterAndImmediate => 2,
+        OperandShape::JumpTarget => 1,
+    };
+    if operands.len() != expected {
+        return Err(AssemblerError::WrongOperandCount {
+            line: line_number,
+            mnemonic: format!("{:?}", opcode),
+            expected,
+            found: operands.len(),
+        });
+    }
+
+    match shape {
+        OperandShape::Zero => {
+            bytes.push(opcode as u8);
+        },
+        OperandShape::OneRegister => {
+            bytes.push(opcode as u8);
+            bytes.push(expect_register(operands.get(0), line_number)?);
+        },
+        OperandShape::TwoRegisters => {
+            bytes.push(opcode as u8);
+            bytes.push(expect_register(operands.get(0), line_number)?);
+            bytes.push(expect_register(operands.get(1), line_number)?);
+        },
+        OperandShape::ThreeRegisters => {
+            bytes.push(opcode as u8);
+            bytes.push(expect_register(operands.get(0), line_number)?);
+            bytes.push(expect_register(operands.get(1), line_number)?);
+            bytes.push(expect_register(operands.get(2), line_number)?);
+        },
+        OperandShape::RegisterAndImmediate => {
+            bytes.push(opcode as u8);
+            bytes.push(expect_register(operands.get(0), line_number)?);
+            match operands.get(1) {
+                Some(Operand::Immediate(value)) => push_immediate(bytes, *value),
+                other => return Err(AssemblerError::InvalidOperand {
+                    line: line_number,
+                    operand: format!("{:?}", other),
+                }),
+            }
+        },
+        OperandShape::JumpTarget => {
+            match operands.get(0) {
+                Some(Operand::Register(r)) => {
+                    bytes.push(opcode as u8);
+                    bytes.push(*r);
+                },
+                Some(Operand::Label(name)) => {
+                    // Undefined labels were already rejected above, so this
+                    // lookup cannot fail.
+                    let addr = labels[name];
+                    bytes.push(Opcode::LOAD as u8);
+                    bytes.push(LABEL_SCRATCH_REGISTER);
+                    push_immediate(bytes, addr as u16);
+                    bytes.push(opcode as u8);
+                    bytes.push(LABEL_SCRATCH_REGISTER);
+                },
+                other => return Err(AssemblerError::InvalidOperand {
+                    line: line_number,
+                    operand: format!("{:?}", other),
+                }),
+            }
+        },
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assemble_load() {
+        let bytes = assemble("LOAD $0 #500").unwrap();
+        assert_eq!(bytes, vec![1, 0, 1, 244]);
+    }
+
+    #[test]
+    fn test_assemble_add() {
+        let bytes = assemble("ADD $0 $1 $2").unwrap();
+        assert_eq!(bytes, vec![2, 0, 1, 2]);
+    }
+
+    #[test]
+    fn test_assemble_is_case_insensitive() {
+        let bytes = assemble("load $0 #10").unwrap();
+        assert_eq!(bytes, vec![1, 0, 0, 10]);
+    }
+
+    #[test]
+    fn test_assemble_jump_to_register() {
+        let bytes = assemble("JMP $0").unwrap();
+        assert_eq!(bytes, vec![7, 0]);
+    }
+
+    #[test]
+    fn test_assemble_label_jump_loads_scratch_register_then_jumps() {
+        let source = "loop: ADD $0 $1 $2\nJMP @loop";
+        let bytes = assemble(source).unwrap();
+        // loop: at offset 0 -> ADD $0 $1 $2 (4 bytes), then
+        // JMP @loop expands to LOAD $31 #0 ; JMP $31
+        assert_eq!(bytes, vec![2, 0, 1, 2, 1, 31, 0, 0, 7, 31]);
+    }
+
+    #[test]
+    fn test_assemble_forward_label_reference() {
+        let source = "JMP @end\nADD $0 $1 $2\nend: HLT";
+        let bytes = assemble(source).unwrap();
+        // JMP @end expands to LOAD $31 #<offset of `end:`> ; JMP $31.
+        // `end:` sits after the 6-byte JMP expansion and the 4-byte ADD, so
+        // its resolved offset is 10.
+        assert_eq!(bytes, vec![1, 31, 0, 10, 7, 31, 2, 0, 1, 2, 0]);
+    }
+
+    #[test]
+    fn test_assemble_undefined_label() {
+        let result = assemble("JMP @missing");
+        assert_eq!(result, Err(AssemblerError::UndefinedLabels(vec!["missing".to_string()])));
+    }
+
+    #[test]
+    fn test_assemble_unknown_mnemonic() {
+        let result = assemble("NOPE $0 $1");
+        assert_eq!(
+            result,
+            Err(AssemblerError::UnknownMnemonic { line: 1, mnemonic: "NOPE".to_string() })
+        );
+    }
+
+    #[test]
+    fn test_assemble_wrong_operand_count() {
+        let result = assemble("ADD $0 $1");
+        assert_eq!(
+            result,
+            Err(AssemblerError::WrongOperandCount {
+                line: 1,
+                mnemonic: "ADD".to_string(),
+                expected: 3,
+                found: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn test_assemble_skips_blank_lines() {
+        let bytes = assemble("\nHLT\n\n").unwrap();
+        assert_eq!(bytes, vec![0]);
+    }
+}
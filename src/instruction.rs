@@ -1,16 +1,36 @@
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum Opcode {
-    HLT,
-    LOAD,
-    ADD,
-    SUB,
-    MUL,
-    DIV,
-    MOV,
-    JMP,
-    JMPB,
-    JMPF,
-    IGL,
+    HLT = 0,
+    LOAD = 1,
+    ADD = 2,
+    SUB = 3,
+    MUL = 4,
+    DIV = 5,
+    MOV = 6,
+    JMP = 7,
+    JMPB = 8,
+    JMPF = 9,
+    LB = 10,
+    LD = 11,
+    SB = 12,
+    SD = 13,
+    ALLOC = 14,
+    EQ = 15,
+    NEQ = 16,
+    GT = 17,
+    LT = 18,
+    GTE = 19,
+    LTE = 20,
+    JEQ = 21,
+    JNE = 22,
+    ADDF = 23,
+    SUBF = 24,
+    MULF = 25,
+    DIVF = 26,
+    DIVU = 27,
+    ECALL = 28,
+    IRET = 29,
+    IGL = 255,
 }
 
 #[derive(Debug, PartialEq)]
@@ -31,6 +51,26 @@ impl From<u8> for Opcode {
             7 => Opcode::JMP,
             8 => Opcode::JMPB,
             9 => Opcode::JMPF,
+            10 => Opcode::LB,
+            11 => Opcode::LD,
+            12 => Opcode::SB,
+            13 => Opcode::SD,
+            14 => Opcode::ALLOC,
+            15 => Opcode::EQ,
+            16 => Opcode::NEQ,
+            17 => Opcode::GT,
+            18 => Opcode::LT,
+            19 => Opcode::GTE,
+            20 => Opcode::LTE,
+            21 => Opcode::JEQ,
+            22 => Opcode::JNE,
+            23 => Opcode::ADDF,
+            24 => Opcode::SUBF,
+            25 => Opcode::MULF,
+            26 => Opcode::DIVF,
+            27 => Opcode::DIVU,
+            28 => Opcode::ECALL,
+            29 => Opcode::IRET,
             _ => Opcode::IGL,
         }
     }
@@ -2,6 +2,7 @@ use std;
 use std::io;
 use std::io::Write;
 use std::num::ParseIntError;
+use crate::assembler;
 use crate::vm::VM;
 
 /// Core struct for the REPL
@@ -37,6 +38,21 @@ impl REPL {
         Ok(results)
     }
 
+    /// Runs every instruction appended to the program that the VM hasn't
+    /// reached yet, stopping as soon as one faults. Only the assembler path
+    /// needs this: a label jump expands into two instructions (a LOAD
+    /// followed by a JMP), so one assembled REPL line can mean more than one
+    /// instruction to run. Raw hex input is always exactly one instruction
+    /// per line and keeps using a single `run_once()` call.
+    fn run_appended_instructions(&mut self) {
+        while self.vm.pc() < self.vm.program.len() {
+            if let Err(e) = self.vm.run_once() {
+                println!("Fault: {:?}", e);
+                break;
+            }
+        }
+    }
+
     pub fn run(&mut self) {
         println!("Firefaith 0.0.1");
         loop {
@@ -70,22 +86,37 @@ impl REPL {
                 "registers()" => {
                     println!("Registers:");
                     println!("{:#?}", self.vm.registers);
+                    println!("Float registers:");
+                    println!("{:#?}", self.vm.float_registers);
                 },
                 _ => {
-                    let results = self.parse_hex(buffer);
-                    match results {
+                    // Hex bytes (e.g. "00 01 02 03") are the fast path and
+                    // always encode exactly one instruction, so it still
+                    // runs with a single `run_once()`. A line that doesn't
+                    // parse as hex is tried as assembly mnemonics instead
+                    // (e.g. "LOAD $0 #500"), which can expand into more than
+                    // one instruction and so runs in a loop.
+                    match self.parse_hex(buffer) {
                         Ok(bytes) => {
                             for byte in bytes {
                                 self.vm.add_byte(byte);
                             }
+                            if let Err(e) = self.vm.run_once() {
+                                println!("Fault: {:?}", e);
+                            }
+                        },
+                        Err(_) => match assembler::assemble(buffer) {
+                            Ok(bytes) => {
+                                for byte in bytes {
+                                    self.vm.add_byte(byte);
+                                }
+                                self.run_appended_instructions();
+                            },
+                            Err(e) => {
+                                println!("Unable to parse input as hex or assembly: {:?}", e);
+                            }
                         },
-                        Err(e) => {
-                            println!("Unable to decode hex string. \
-                                Please enter 4 hex bytes.");
-                        }
                     };
-
-                    self.vm.run_once();
                 }
             }
         }
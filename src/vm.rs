@@ -1,10 +1,112 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+
 use crate::instruction::Opcode;
 
+/// Heap starts out with this many zeroed bytes already available so small
+/// programs can use memory opcodes without an explicit ALLOC first.
+const DEFAULT_HEAP_SIZE: usize = 64;
+
+/// Upper bound on how large the heap will grow to service a single memory
+/// opcode or `ALLOC`. Addresses are register-derived `i32`s, so without this
+/// a bad address could force a multi-gigabyte `Vec::resize` and abort the
+/// process on allocation failure rather than raising a catchable fault.
+const MAX_HEAP_SIZE: usize = 16 * 1024 * 1024;
+
+/// A fault raised while decoding or executing an instruction. Every fault is
+/// recoverable: the caller (the REPL or a host embedding the VM) decides
+/// whether to stop, log and continue, or reset the machine.
+#[derive(Debug, PartialEq)]
+pub enum VmError {
+    DivideByZero,
+    InvalidRegister(u8),
+    ProgramOutOfBounds,
+    IllegalOpcode(u8),
+    MemoryFault(usize),
+    UnknownSyscall(u32),
+    InterruptStackUnderflow,
+    Io(String),
+}
+
+/// A handler invoked by `ECALL`. By convention it reads its arguments out of
+/// `registers[1]`/`registers[2]` and returns `Ok(true)` to keep running or
+/// `Ok(false)` to halt the VM cleanly, mirroring `execute_instruction`.
+pub type SyscallHandler = fn(&mut VM) -> Result<bool, VmError>;
+
+/// Stops the VM, printing the exit code held in `registers[1]`.
+pub const SC_SHUTDOWN: u32 = 0;
+/// Writes `registers[2]` bytes from `heap[registers[1]..]` to stdout.
+pub const SC_WRITE: u32 = 1;
+/// Reads `registers[2]` bytes from stdin into `heap[registers[1]..]`.
+pub const SC_READ: u32 = 2;
+
+fn sc_shutdown(vm: &mut VM) -> Result<bool, VmError> {
+    println!("Process exited with code {}", vm.registers[1]);
+    Ok(false)
+}
+
+fn sc_write(vm: &mut VM) -> Result<bool, VmError> {
+    let addr = vm.registers[1];
+    let len = vm.registers[2];
+    if addr < 0 || len < 0 {
+        return Err(VmError::MemoryFault(addr.max(0) as usize));
+    }
+    let (addr, len) = (addr as usize, len as usize);
+    let end = addr.checked_add(len).ok_or(VmError::MemoryFault(addr))?;
+    if end > vm.heap.len() {
+        return Err(VmError::MemoryFault(addr));
+    }
+
+    io::stdout().write_all(&vm.heap[addr..end])
+        .map_err(|e| VmError::Io(e.to_string()))?;
+    Ok(true)
+}
+
+fn sc_read(vm: &mut VM) -> Result<bool, VmError> {
+    let addr = vm.registers[1];
+    let len = vm.registers[2];
+    if addr < 0 || len < 0 {
+        return Err(VmError::MemoryFault(addr.max(0) as usize));
+    }
+    let (addr, len) = (addr as usize, len as usize);
+    vm.ensure_heap_capacity(addr + len)?;
+
+    let mut buf = vec![0u8; len];
+    io::stdin().read_exact(&mut buf)
+        .map_err(|e| VmError::Io(e.to_string()))?;
+    vm.heap[addr..addr + len].copy_from_slice(&buf);
+    Ok(true)
+}
+
 pub struct VM {
     pub registers: [i32; 32],
     pc: usize,
     pub program: Vec<u8>,
+    pub heap: Vec<u8>,
     remainder: u32,
+    /// Set by the comparison opcodes (EQ/NEQ/GT/LT/GTE/LTE) and read by the
+    /// conditional jumps (JEQ/JNE).
+    pub equal_flag: bool,
+    /// Parallel bank of floating-point registers for the ADDF/SUBF/MULF/DIVF
+    /// opcodes, decoded the same way as the integer registers.
+    pub float_registers: [f64; 32],
+    /// Handlers dispatched by `ECALL`, keyed by syscall number. Hosts can
+    /// register their own alongside (or in place of) the defaults.
+    pub syscalls: HashMap<u32, SyscallHandler>,
+    /// Number of execution steps taken so far: one per `execute_instruction`
+    /// call that finds `pc` inside the program, including a step in which a
+    /// timer interrupt redirects `pc` instead of running an instruction.
+    /// Wraps around at `u64::MAX` rather than overflowing.
+    pub cycle_count: u64,
+    /// When set, a timer interrupt fires every `timer_period` cycles.
+    pub timer_period: Option<u32>,
+    /// Program address the timer interrupt jumps to when it fires.
+    pub timer_handler: Option<usize>,
+    /// Master switch for the timer; interrupts never fire while this is
+    /// `false`, even if a period and handler are configured.
+    pub interrupts_enabled: bool,
+    /// Return addresses saved by timer interrupts, popped by `IRET`.
+    interrupt_stack: Vec<usize>,
 }
 
 impl VM {
@@ -13,115 +115,398 @@ impl VM {
             registers: [0; 32],
             pc: 0,
             program: vec![],
+            heap: vec![0; DEFAULT_HEAP_SIZE],
             remainder: 0,
+            equal_flag: false,
+            float_registers: [0.0; 32],
+            syscalls: {
+                let mut syscalls: HashMap<u32, SyscallHandler> = HashMap::new();
+                syscalls.insert(SC_SHUTDOWN, sc_shutdown);
+                syscalls.insert(SC_WRITE, sc_write);
+                syscalls.insert(SC_READ, sc_read);
+                syscalls
+            },
+            cycle_count: 0,
+            timer_period: None,
+            timer_handler: None,
+            interrupts_enabled: false,
+            interrupt_stack: vec![],
+        }
+    }
+
+    /// Checks whether a timer interrupt is due and, if so, saves `self.pc`
+    /// and redirects execution to `timer_handler`. Returns `true` if an
+    /// interrupt was dispatched, meaning the instruction at the saved `pc`
+    /// has not run yet this call.
+    fn maybe_dispatch_timer_interrupt(&mut self) -> bool {
+        if !self.interrupts_enabled {
+            return false;
+        }
+        let period = match self.timer_period {
+            Some(period) if period != 0 => period,
+            _ => return false,
+        };
+        if self.cycle_count % period as u64 != 0 {
+            return false;
+        }
+        match self.timer_handler {
+            Some(handler) => {
+                self.interrupt_stack.push(self.pc);
+                self.pc = handler;
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Ensures the heap is at least `len` bytes long, zero-filling any newly
+    /// added space, so out-of-range memory opcodes grow the heap instead of
+    /// panicking. Faults instead of growing past `MAX_HEAP_SIZE`, since an
+    /// unbounded resize driven by a bad register value could abort the
+    /// process on allocation failure.
+    fn ensure_heap_capacity(&mut self, len: usize) -> Result<(), VmError> {
+        if len > MAX_HEAP_SIZE {
+            return Err(VmError::MemoryFault(len));
+        }
+        if self.heap.len() < len {
+            self.heap.resize(len, 0);
         }
+        Ok(())
     }
 
     /// Decode the opcode from an instruction and return it
-    fn decode_opcode(&mut self) -> Opcode {
-        let opcode = Opcode::from(self.program[self.pc]);
-        self.pc += 1;
-        return opcode;
+    fn decode_opcode(&mut self) -> Result<Opcode, VmError> {
+        let byte = self.next_byte()?;
+        Ok(Opcode::from(byte))
     }
 
     pub fn add_byte(&mut self, byte: u8) {
         self.program.push(byte);
     }
 
+    /// Returns the current program counter, mainly so a host (like the
+    /// REPL) can tell how much of a just-appended program still needs to
+    /// run.
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
     /// Helper function to return the next byte of the instruction
-    fn next_byte(&mut self) -> u8 {
+    fn next_byte(&mut self) -> Result<u8, VmError> {
+        if self.pc >= self.program.len() {
+            return Err(VmError::ProgramOutOfBounds);
+        }
         let result = self.program[self.pc];
         self.pc += 1;
-        return result;
+        Ok(result)
     }
 
     /// Helper function to return the next two bytes of the instruction
-    fn next_short(&mut self) -> u16 {
-        let result = ((self.program[self.pc] as u16) << 8) | 
+    fn next_short(&mut self) -> Result<u16, VmError> {
+        if self.pc + 1 >= self.program.len() {
+            return Err(VmError::ProgramOutOfBounds);
+        }
+        let result = ((self.program[self.pc] as u16) << 8) |
             self.program[self.pc + 1] as u16;
         self.pc += 2;
-        return result;
+        Ok(result)
+    }
+
+    /// Reads the next byte of the instruction as a register index and
+    /// returns the value currently held in that register.
+    fn next_register(&mut self) -> Result<i32, VmError> {
+        let index = self.next_byte()?;
+        self.register(index)
+    }
+
+    /// Returns the value held in register `index`, or `InvalidRegister` if
+    /// `index` is out of range.
+    fn register(&self, index: u8) -> Result<i32, VmError> {
+        self.registers.get(index as usize)
+            .copied()
+            .ok_or(VmError::InvalidRegister(index))
+    }
+
+    /// Writes `value` into register `index`, or returns `InvalidRegister` if
+    /// `index` is out of range.
+    fn set_register(&mut self, index: u8, value: i32) -> Result<(), VmError> {
+        self.registers.get_mut(index as usize)
+            .map(|r| *r = value)
+            .ok_or(VmError::InvalidRegister(index))
+    }
+
+    /// Reads the next byte of the instruction as a register index and
+    /// returns the value currently held in that float register.
+    fn next_float_register(&mut self) -> Result<f64, VmError> {
+        let index = self.next_byte()?;
+        self.float_registers.get(index as usize)
+            .copied()
+            .ok_or(VmError::InvalidRegister(index))
+    }
+
+    /// Writes `value` into float register `index`, or returns
+    /// `InvalidRegister` if `index` is out of range.
+    fn set_float_register(&mut self, index: u8, value: f64) -> Result<(), VmError> {
+        self.float_registers.get_mut(index as usize)
+            .map(|r| *r = value)
+            .ok_or(VmError::InvalidRegister(index))
     }
 
-    /// Loops as long as instructions can be executed
-    pub fn run(&mut self) {
+    /// Resolves a register holding a heap address into a non-negative
+    /// offset, growing the heap if needed, or faults if the address is
+    /// negative.
+    fn heap_address(&mut self, addr: i32, len: usize) -> Result<usize, VmError> {
+        if addr < 0 {
+            return Err(VmError::MemoryFault(addr as usize));
+        }
+        let addr = addr as usize;
+        self.ensure_heap_capacity(addr + len)?;
+        Ok(addr)
+    }
+
+    /// Loops as long as instructions can be executed, stopping on the first
+    /// fault and returning it to the caller.
+    pub fn run(&mut self) -> Result<(), VmError> {
         let mut is_done = false;
         while !is_done {
-            is_done = self.execute_instruction();
+            is_done = !self.execute_instruction()?;
         }
+        Ok(())
     }
 
     /// Executes one instruction. Meant to allow for more controlled execution
     /// of the VM
-    pub fn run_once(&mut self) {
-        self.execute_instruction();
+    pub fn run_once(&mut self) -> Result<(), VmError> {
+        self.execute_instruction()?;
+        Ok(())
     }
 
-    /// Executes an instruction
-    fn execute_instruction(&mut self) -> bool {
+    /// Executes an instruction. Returns `Ok(true)` if execution should
+    /// continue, `Ok(false)` if the VM halted cleanly, or `Err` on a fault.
+    fn execute_instruction(&mut self) -> Result<bool, VmError> {
         // Ensure PC is not invalid
         if self.pc >= self.program.len() {
-            return false;
+            return Ok(false);
         }
 
+        self.cycle_count = self.cycle_count.wrapping_add(1);
+        if self.maybe_dispatch_timer_interrupt() {
+            return Ok(true);
+        }
+
+        let opcode_byte = self.program[self.pc];
+
         // Match opcodes
-        match self.decode_opcode() {
+        match self.decode_opcode()? {
             Opcode::LOAD => {
-                let register = self.next_byte() as usize;
-                let number   = self.next_short() as u32;
-                self.registers[register] = number as i32;
+                let register = self.next_byte()?;
+                let number   = self.next_short()? as u32;
+                self.set_register(register, number as i32)?;
             },
             Opcode::HLT => {
                 println!("HLT");
-                return false;
+                return Ok(false);
             },
             Opcode::ADD => {
-                let reg1 = self.registers[self.next_byte() as usize];
-                let reg2 = self.registers[self.next_byte() as usize];
-                self.registers[self.next_byte() as usize] = reg1 + reg2;
+                let reg1 = self.next_register()?;
+                let reg2 = self.next_register()?;
+                let dest = self.next_byte()?;
+                self.set_register(dest, reg1 + reg2)?;
             },
             Opcode::SUB => {
-                let reg1 = self.registers[self.next_byte() as usize];
-                let reg2 = self.registers[self.next_byte() as usize];
-                self.registers[self.next_byte() as usize] = reg1 - reg2;
+                let reg1 = self.next_register()?;
+                let reg2 = self.next_register()?;
+                let dest = self.next_byte()?;
+                self.set_register(dest, reg1 - reg2)?;
             },
             Opcode::MUL => {
-                let reg1 = self.registers[self.next_byte() as usize];
-                let reg2 = self.registers[self.next_byte() as usize];
-                self.registers[self.next_byte() as usize] = reg1 * reg2;
+                let reg1 = self.next_register()?;
+                let reg2 = self.next_register()?;
+                let dest = self.next_byte()?;
+                self.set_register(dest, reg1 * reg2)?;
             },
             Opcode::DIV => {
-                let reg1 = self.registers[self.next_byte() as usize];
-                let reg2 = self.registers[self.next_byte() as usize];
-                self.registers[self.next_byte() as usize] = reg1 / reg2;
+                let reg1 = self.next_register()?;
+                let reg2 = self.next_register()?;
+                let dest = self.next_byte()?;
+                if reg2 == 0 {
+                    return Err(VmError::DivideByZero);
+                }
+                self.set_register(dest, reg1 / reg2)?;
                 self.remainder = (reg1 % reg2) as u32;
             },
             Opcode::MOV => {
-                let reg1 = self.next_byte() as usize;
-                let reg2 = self.registers[self.next_byte() as usize];
-                self.registers[reg1] = reg2 as i32;
+                let reg1 = self.next_byte()?;
+                let reg2 = self.next_register()?;
+                self.set_register(reg1, reg2)?;
             },
             Opcode::JMP => {
-                let target = self.registers[self.next_byte() as usize];
+                let target = self.next_register()?;
                 self.pc = target as usize;
             },
             Opcode::JMPB => {
-                let value = self.registers[self.next_byte() as usize];
-                self.pc -= value as usize;                
+                let value = self.next_register()? as usize;
+                self.pc = self.pc.checked_sub(value)
+                    .ok_or(VmError::ProgramOutOfBounds)?;
             },
 
             Opcode::JMPF => {
-                let value = self.registers[self.next_byte() as usize];
-                self.pc += value as usize;
+                let value = self.next_register()? as usize;
+                self.pc = self.pc.checked_add(value)
+                    .ok_or(VmError::ProgramOutOfBounds)?;
+            },
+
+            Opcode::LB => {
+                let dest = self.next_byte()?;
+                let addr = self.next_register()?;
+                let addr = self.heap_address(addr, 1)?;
+                self.set_register(dest, self.heap[addr] as i32)?;
+            },
+
+            Opcode::LD => {
+                let dest = self.next_byte()?;
+                let addr = self.next_register()?;
+                let addr = self.heap_address(addr, 4)?;
+                let word = (self.heap[addr] as u32)
+                    | ((self.heap[addr + 1] as u32) << 8)
+                    | ((self.heap[addr + 2] as u32) << 16)
+                    | ((self.heap[addr + 3] as u32) << 24);
+                self.set_register(dest, word as i32)?;
+            },
+
+            Opcode::SB => {
+                let addr = self.next_register()?;
+                let value = self.next_register()?;
+                let addr = self.heap_address(addr, 1)?;
+                self.heap[addr] = value as u8;
+            },
+
+            Opcode::SD => {
+                let addr = self.next_register()?;
+                let value = self.next_register()? as u32;
+                let addr = self.heap_address(addr, 4)?;
+                self.heap[addr] = (value & 0xff) as u8;
+                self.heap[addr + 1] = ((value >> 8) & 0xff) as u8;
+                self.heap[addr + 2] = ((value >> 16) & 0xff) as u8;
+                self.heap[addr + 3] = ((value >> 24) & 0xff) as u8;
+            },
+
+            Opcode::ALLOC => {
+                let extra = self.next_register()?;
+                if extra < 0 {
+                    return Err(VmError::MemoryFault(extra as usize));
+                }
+                let new_len = self.heap.len() + extra as usize;
+                self.ensure_heap_capacity(new_len)?;
+            },
+
+            Opcode::EQ => {
+                let reg1 = self.next_register()?;
+                let reg2 = self.next_register()?;
+                self.equal_flag = reg1 == reg2;
+            },
+
+            Opcode::NEQ => {
+                let reg1 = self.next_register()?;
+                let reg2 = self.next_register()?;
+                self.equal_flag = reg1 != reg2;
+            },
+
+            Opcode::GT => {
+                let reg1 = self.next_register()?;
+                let reg2 = self.next_register()?;
+                self.equal_flag = reg1 > reg2;
+            },
+
+            Opcode::LT => {
+                let reg1 = self.next_register()?;
+                let reg2 = self.next_register()?;
+                self.equal_flag = reg1 < reg2;
+            },
+
+            Opcode::GTE => {
+                let reg1 = self.next_register()?;
+                let reg2 = self.next_register()?;
+                self.equal_flag = reg1 >= reg2;
+            },
+
+            Opcode::LTE => {
+                let reg1 = self.next_register()?;
+                let reg2 = self.next_register()?;
+                self.equal_flag = reg1 <= reg2;
+            },
+
+            Opcode::JEQ => {
+                let target = self.next_register()?;
+                if self.equal_flag {
+                    self.pc = target as usize;
+                }
+            },
+
+            Opcode::JNE => {
+                let target = self.next_register()?;
+                if !self.equal_flag {
+                    self.pc = target as usize;
+                }
+            },
+
+            Opcode::ADDF => {
+                let reg1 = self.next_float_register()?;
+                let reg2 = self.next_float_register()?;
+                let dest = self.next_byte()?;
+                self.set_float_register(dest, reg1 + reg2)?;
+            },
+
+            Opcode::SUBF => {
+                let reg1 = self.next_float_register()?;
+                let reg2 = self.next_float_register()?;
+                let dest = self.next_byte()?;
+                self.set_float_register(dest, reg1 - reg2)?;
+            },
+
+            Opcode::MULF => {
+                let reg1 = self.next_float_register()?;
+                let reg2 = self.next_float_register()?;
+                let dest = self.next_byte()?;
+                self.set_float_register(dest, reg1 * reg2)?;
+            },
+
+            Opcode::DIVF => {
+                let reg1 = self.next_float_register()?;
+                let reg2 = self.next_float_register()?;
+                let dest = self.next_byte()?;
+                self.set_float_register(dest, reg1 / reg2)?;
+            },
+
+            Opcode::DIVU => {
+                let reg1 = self.next_register()? as u32;
+                let reg2 = self.next_register()? as u32;
+                let dest = self.next_byte()?;
+                if reg2 == 0 {
+                    return Err(VmError::DivideByZero);
+                }
+                self.set_register(dest, (reg1 / reg2) as i32)?;
+                self.remainder = reg1 % reg2;
+            },
+
+            Opcode::ECALL => {
+                let number = self.next_register()? as u32;
+                let handler = *self.syscalls.get(&number)
+                    .ok_or(VmError::UnknownSyscall(number))?;
+                return handler(self);
+            },
+
+            Opcode::IRET => {
+                self.pc = self.interrupt_stack.pop()
+                    .ok_or(VmError::InterruptStackUnderflow)?;
             },
 
             Opcode::IGL => {
-                println!("Illegal instruction");
-                return false;
+                return Err(VmError::IllegalOpcode(opcode_byte));
             }
         }
 
-        true
+        Ok(true)
     }
 }
 
@@ -148,7 +533,7 @@ mod tests {
         let mut test_vm = VM::new();
         let test_bytes = vec![0,0,0,0];
         test_vm.program = test_bytes;
-        test_vm.run_once();
+        test_vm.run_once().unwrap();
         assert_eq!(test_vm.pc, 1);
     }
 
@@ -157,8 +542,285 @@ mod tests {
         let mut test_vm = VM::new();
         let test_bytes = vec![255,0,0,0];
         test_vm.program = test_bytes;
-        test_vm.run_once();
+        let result = test_vm.run_once();
+        assert_eq!(result, Err(VmError::IllegalOpcode(255)));
+        assert_eq!(test_vm.pc, 1);
+    }
+
+    #[test]
+    fn test_divide_by_zero_faults_instead_of_panicking() {
+        let mut test_vm = get_test_vm();
+        test_vm.registers[1] = 0;
+        let test_bytes = vec![5, 0, 1, 2]; // DIV $0 $1 $2
+        test_vm.program = test_bytes;
+        let result = test_vm.run_once();
+        assert_eq!(result, Err(VmError::DivideByZero));
+    }
+
+    #[test]
+    fn test_program_out_of_bounds_faults_instead_of_panicking() {
+        let mut test_vm = VM::new();
+        let test_bytes = vec![2, 0, 1]; // ADD $0 $1 <missing dest register>
+        test_vm.program = test_bytes;
+        let result = test_vm.run_once();
+        assert_eq!(result, Err(VmError::ProgramOutOfBounds));
+    }
+
+    #[test]
+    fn test_opcode_eq() {
+        let mut test_vm = get_test_vm();
+        test_vm.registers[2] = 10;
+        let test_bytes = vec![15, 0, 2]; // EQ $0 $2
+        test_vm.program = test_bytes;
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.equal_flag, true);
+    }
+
+    #[test]
+    fn test_opcode_neq() {
+        let mut test_vm = get_test_vm();
+        let test_bytes = vec![16, 0, 1]; // NEQ $0 $1
+        test_vm.program = test_bytes;
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.equal_flag, true);
+    }
+
+    #[test]
+    fn test_opcode_gt_lt() {
+        let mut test_vm = get_test_vm();
+        let test_bytes = vec![
+            17, 1, 0, // GT $1 $0 (20 > 10)
+            18, 1, 0, // LT $1 $0 (20 < 10)
+        ];
+        test_vm.program = test_bytes;
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.equal_flag, true);
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.equal_flag, false);
+    }
+
+    #[test]
+    fn test_opcode_gte_lte() {
+        let mut test_vm = get_test_vm();
+        test_vm.registers[2] = 10;
+        let test_bytes = vec![
+            19, 0, 2, // GTE $0 $2 (10 >= 10)
+            20, 0, 2, // LTE $0 $2 (10 <= 10)
+        ];
+        test_vm.program = test_bytes;
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.equal_flag, true);
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.equal_flag, true);
+    }
+
+    #[test]
+    fn test_opcode_jeq() {
+        let mut test_vm = get_test_vm();
+        test_vm.equal_flag = true;
+        test_vm.registers[0] = 7;
+        let test_bytes = vec![21, 0, 0, 0]; // JEQ $0
+        test_vm.program = test_bytes;
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.pc, 7);
+    }
+
+    #[test]
+    fn test_opcode_jeq_not_taken() {
+        let mut test_vm = get_test_vm();
+        test_vm.equal_flag = false;
+        test_vm.registers[0] = 7;
+        let test_bytes = vec![21, 0, 0, 0]; // JEQ $0
+        test_vm.program = test_bytes;
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.pc, 2);
+    }
+
+    #[test]
+    fn test_opcode_jne() {
+        let mut test_vm = get_test_vm();
+        test_vm.equal_flag = false;
+        test_vm.registers[0] = 7;
+        let test_bytes = vec![22, 0, 0, 0]; // JNE $0
+        test_vm.program = test_bytes;
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.pc, 7);
+    }
+
+    #[test]
+    fn test_opcode_addf() {
+        let mut test_vm = VM::new();
+        test_vm.float_registers[0] = 1.5;
+        test_vm.float_registers[1] = 2.25;
+        let test_bytes = vec![23, 0, 1, 2]; // ADDF $0 $1 $2
+        test_vm.program = test_bytes;
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.float_registers[2], 3.75);
+    }
+
+    #[test]
+    fn test_opcode_subf() {
+        let mut test_vm = VM::new();
+        test_vm.float_registers[0] = 5.0;
+        test_vm.float_registers[1] = 2.0;
+        let test_bytes = vec![24, 0, 1, 2]; // SUBF $0 $1 $2
+        test_vm.program = test_bytes;
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.float_registers[2], 3.0);
+    }
+
+    #[test]
+    fn test_opcode_mulf() {
+        let mut test_vm = VM::new();
+        test_vm.float_registers[0] = 2.5;
+        test_vm.float_registers[1] = 4.0;
+        let test_bytes = vec![25, 0, 1, 2]; // MULF $0 $1 $2
+        test_vm.program = test_bytes;
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.float_registers[2], 10.0);
+    }
+
+    #[test]
+    fn test_opcode_divf() {
+        let mut test_vm = VM::new();
+        test_vm.float_registers[0] = 9.0;
+        test_vm.float_registers[1] = 2.0;
+        let test_bytes = vec![26, 0, 1, 2]; // DIVF $0 $1 $2
+        test_vm.program = test_bytes;
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.float_registers[2], 4.5);
+    }
+
+    #[test]
+    fn test_opcode_divu() {
+        let mut test_vm = VM::new();
+        test_vm.registers[0] = -1; // 0xffffffff as u32
+        test_vm.registers[1] = 2;
+        let test_bytes = vec![27, 0, 1, 2]; // DIVU $0 $1 $2
+        test_vm.program = test_bytes;
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.registers[2] as u32, 0xffffffffu32 / 2);
+    }
+
+    #[test]
+    fn test_opcode_divu_by_zero_faults() {
+        let mut test_vm = VM::new();
+        test_vm.registers[0] = 4;
+        test_vm.registers[1] = 0;
+        let test_bytes = vec![27, 0, 1, 2]; // DIVU $0 $1 $2
+        test_vm.program = test_bytes;
+        let result = test_vm.run_once();
+        assert_eq!(result, Err(VmError::DivideByZero));
+    }
+
+    #[test]
+    fn test_opcode_ecall_shutdown_halts_the_vm() {
+        let mut test_vm = VM::new();
+        test_vm.registers[0] = SC_SHUTDOWN as i32;
+        test_vm.registers[1] = 42; // exit code
+        let test_bytes = vec![28, 0]; // ECALL $0
+        test_vm.program = test_bytes;
+        let result = test_vm.run();
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn test_opcode_ecall_write_reads_from_heap() {
+        let mut test_vm = VM::new();
+        test_vm.heap[0] = b'h';
+        test_vm.heap[1] = b'i';
+        test_vm.registers[0] = SC_WRITE as i32;
+        test_vm.registers[1] = 0; // heap address
+        test_vm.registers[2] = 2; // length
+        let test_bytes = vec![28, 0]; // ECALL $0
+        test_vm.program = test_bytes;
+        test_vm.run_once().unwrap();
+    }
+
+    #[test]
+    fn test_opcode_ecall_unknown_syscall_traps() {
+        let mut test_vm = VM::new();
+        test_vm.registers[0] = 999;
+        let test_bytes = vec![28, 0]; // ECALL $0
+        test_vm.program = test_bytes;
+        let result = test_vm.run_once();
+        assert_eq!(result, Err(VmError::UnknownSyscall(999)));
+    }
+
+    #[test]
+    fn test_opcode_ecall_dispatches_custom_handler() {
+        fn double_register_one(vm: &mut VM) -> Result<bool, VmError> {
+            vm.registers[1] *= 2;
+            Ok(true)
+        }
+
+        let mut test_vm = VM::new();
+        test_vm.syscalls.insert(100, double_register_one);
+        test_vm.registers[0] = 100;
+        test_vm.registers[1] = 21;
+        let test_bytes = vec![28, 0]; // ECALL $0
+        test_vm.program = test_bytes;
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.registers[1], 42);
+    }
+
+    #[test]
+    fn test_timer_interrupt_saves_pc_and_iret_restores_it() {
+        let mut test_vm = get_test_vm();
+        test_vm.interrupts_enabled = true;
+        test_vm.timer_period = Some(2);
+        test_vm.timer_handler = Some(10);
+        let test_bytes = vec![
+            2, 0, 1, 2, // offset 0: ADD $0 $1 $2
+            0,          // offset 4: HLT
+            0, 0, 0, 0, 0, // unreached padding
+            29,         // offset 10: IRET
+        ];
+        test_vm.program = test_bytes;
+
+        test_vm.run_once().unwrap(); // cycle 1: no interrupt, ADD runs
+        assert_eq!(test_vm.registers[2], 30);
+        assert_eq!(test_vm.pc, 4);
+
+        test_vm.run_once().unwrap(); // cycle 2: interrupt fires instead of HLT
+        assert_eq!(test_vm.pc, 10);
+        assert_eq!(test_vm.interrupt_stack, vec![4]);
+
+        test_vm.run_once().unwrap(); // cycle 3: IRET restores the saved pc
+        assert_eq!(test_vm.pc, 4);
+        assert!(test_vm.interrupt_stack.is_empty());
+    }
+
+    #[test]
+    fn test_timer_interrupt_does_not_fire_when_disabled() {
+        let mut test_vm = VM::new();
+        test_vm.interrupts_enabled = false;
+        test_vm.timer_period = Some(1);
+        test_vm.timer_handler = Some(10);
+        let test_bytes = vec![0, 0, 0, 0]; // HLT
+        test_vm.program = test_bytes;
+        test_vm.run_once().unwrap();
         assert_eq!(test_vm.pc, 1);
+        assert!(test_vm.interrupt_stack.is_empty());
+    }
+
+    #[test]
+    fn test_iret_with_empty_stack_faults() {
+        let mut test_vm = VM::new();
+        let test_bytes = vec![29]; // IRET
+        test_vm.program = test_bytes;
+        let result = test_vm.run_once();
+        assert_eq!(result, Err(VmError::InterruptStackUnderflow));
+    }
+
+    #[test]
+    fn test_cycle_count_wraps_around_instead_of_overflowing() {
+        let mut test_vm = VM::new();
+        test_vm.cycle_count = u64::MAX;
+        let test_bytes = vec![0]; // HLT
+        test_vm.program = test_bytes;
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.cycle_count, 0);
     }
 
     #[test]
@@ -167,7 +829,7 @@ mod tests {
         // [1,244] is 0x1f4 in little endian, which is 500
         let test_bytes = vec![1,0,1,244]; // LOAD $0 0x1f4
         test_vm.program = test_bytes;
-        test_vm.run();
+        test_vm.run().unwrap();
         assert_eq!(test_vm.registers[0], 500);
     }
 
@@ -176,7 +838,7 @@ mod tests {
         let mut test_vm = get_test_vm();
         let test_bytes = vec![2, 0, 1, 2]; // ADD $0 $1 $2
         test_vm.program = test_bytes;
-        test_vm.run();
+        test_vm.run().unwrap();
         assert_eq!(test_vm.registers[2], 0x1e);
     }
 
@@ -185,7 +847,7 @@ mod tests {
         let mut test_vm = get_test_vm();
         let test_bytes = vec![3, 1, 0, 2]; // SUB $1 $0 $2
         test_vm.program = test_bytes;
-        test_vm.run();
+        test_vm.run().unwrap();
         assert_eq!(test_vm.registers[2], 0x0a);
     }
 
@@ -194,7 +856,7 @@ mod tests {
         let mut test_vm = get_test_vm();
         let test_bytes = vec![4, 0, 1, 2]; // MUL $0 $1 $2
         test_vm.program = test_bytes;
-        test_vm.run();
+        test_vm.run().unwrap();
         assert_eq!(test_vm.registers[2], 0xc8);
     }
 
@@ -203,7 +865,7 @@ mod tests {
         let mut test_vm = get_test_vm();
         let test_bytes = vec![5, 1, 0, 2]; // DIV $1 $0 $2
         test_vm.program = test_bytes;
-        test_vm.run();
+        test_vm.run().unwrap();
         assert_eq!(test_vm.registers[2], 0x2);
     }
 
@@ -212,7 +874,7 @@ mod tests {
         let mut test_vm = get_test_vm();
         let test_bytes = vec![5, 0, 1, 2]; // DIV $0 $1 $2
         test_vm.program = test_bytes;
-        test_vm.run();
+        test_vm.run().unwrap();
         assert_eq!(test_vm.registers[2], 0);
         assert_eq!(test_vm.remainder, 10);
     }
@@ -223,7 +885,7 @@ mod tests {
         let test_bytes = vec![6, 0, 1, 0]; // MOV $0 $1
         println!("{} {}", test_vm.registers[0], test_vm.registers[1]);
         test_vm.program = test_bytes;
-        test_vm.run();
+        test_vm.run().unwrap();
         println!("{} {}", test_vm.registers[0], test_vm.registers[1]);
         assert_eq!(test_vm.registers[0], 0x14);
         assert_eq!(test_vm.registers[1], 0x14);
@@ -235,7 +897,7 @@ mod tests {
         let test_bytes = vec![7,0,0,0]; // JMP $0
         test_vm.program = test_bytes;
         test_vm.pc = 0;
-        test_vm.run_once();
+        test_vm.run_once().unwrap();
         assert_eq!(test_vm.pc, 10);
     }
 
@@ -245,18 +907,88 @@ mod tests {
         let test_bytes = vec![0,0,0,0,0,0,0,0,0,0,0,0,8,0,0,0]; // JMPB $0
         test_vm.program = test_bytes;
         test_vm.pc = 12;
-        test_vm.run_once();
+        test_vm.run_once().unwrap();
         assert_eq!(test_vm.pc, 4); // pc gets incremented by 2 before the JMPB
     }
 
-    #[test]    
+    #[test]
     fn test_opcode_jmpf() {
         let mut test_vm = get_test_vm();
         let test_bytes = vec![9,0,0,0]; // JMPB $0
         test_vm.program = test_bytes;
-        test_vm.run_once();
+        test_vm.run_once().unwrap();
         assert_eq!(test_vm.pc, 12); // pc gets incremented by 2 before the JMPF
     }
 
+    #[test]
+    fn test_opcode_sb_lb() {
+        let mut test_vm = VM::new();
+        test_vm.registers[0] = 4; // address
+        test_vm.registers[1] = 0xab; // value to store
+        let test_bytes = vec![
+            12, 0, 1,    // SB $0 $1 (heap[4] = 0xab) -- 3 bytes, no padding
+            10, 2, 0, 0, // LB $2 $0 (registers[2] = heap[4])
+        ];
+        test_vm.program = test_bytes;
+        test_vm.run().unwrap();
+        assert_eq!(test_vm.heap[4], 0xab);
+        assert_eq!(test_vm.registers[2], 0xab);
+    }
+
+    #[test]
+    fn test_opcode_sd_ld() {
+        let mut test_vm = VM::new();
+        test_vm.registers[0] = 8; // address
+        test_vm.registers[1] = 0x01020304; // value to store
+        let test_bytes = vec![
+            13, 0, 1,    // SD $0 $1 (heap[8..12] = 0x01020304 little-endian) -- 3 bytes, no padding
+            11, 2, 0, 0, // LD $2 $0 (registers[2] = heap[8..12])
+        ];
+        test_vm.program = test_bytes;
+        test_vm.run().unwrap();
+        assert_eq!(test_vm.registers[2], 0x01020304);
+    }
+
+    #[test]
+    fn test_opcode_alloc_grows_heap() {
+        let mut test_vm = VM::new();
+        let starting_len = test_vm.heap.len();
+        test_vm.registers[0] = 16;
+        let test_bytes = vec![14, 0, 0, 0]; // ALLOC $0
+        test_vm.program = test_bytes;
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.heap.len(), starting_len + 16);
+    }
+
+    #[test]
+    fn test_memory_opcode_grows_heap_on_out_of_range_address() {
+        let mut test_vm = VM::new();
+        test_vm.registers[0] = 1000; // well beyond the default heap size
+        test_vm.registers[1] = 7;
+        let test_bytes = vec![12, 0, 1, 0]; // SB $0 $1
+        test_vm.program = test_bytes;
+        test_vm.run_once().unwrap();
+        assert_eq!(test_vm.heap[1000], 7);
+    }
 
+    #[test]
+    fn test_memory_opcode_faults_instead_of_growing_heap_unbounded() {
+        let mut test_vm = VM::new();
+        test_vm.registers[0] = i32::MAX; // way past any sane heap size
+        test_vm.registers[1] = 7;
+        let test_bytes = vec![12, 0, 1, 0]; // SB $0 $1
+        test_vm.program = test_bytes;
+        let result = test_vm.run_once();
+        assert!(matches!(result, Err(VmError::MemoryFault(_))));
+    }
+
+    #[test]
+    fn test_alloc_faults_instead_of_growing_heap_unbounded() {
+        let mut test_vm = VM::new();
+        test_vm.registers[0] = i32::MAX;
+        let test_bytes = vec![14, 0, 0, 0]; // ALLOC $0
+        test_vm.program = test_bytes;
+        let result = test_vm.run_once();
+        assert!(matches!(result, Err(VmError::MemoryFault(_))));
+    }
 }